@@ -1,5 +1,5 @@
 use crate::bank_account::roles::{ReceiveRole, SenderRole};
-use crate::money::{Money, MoneyError};
+use crate::money::{Money, MoneyError, NonNegative};
 
 #[derive(Debug, Clone, Copy)]
 pub struct BankAccountId(pub(crate) u32);
@@ -12,29 +12,33 @@ pub struct UserAccountId(pub(crate) u32);
 pub struct BankAccount {
   id: BankAccountId,
   user_account_id: UserAccountId,
-  balance: Money,
+  balance: Money<NonNegative>,
 }
 
 /// コンテキストに非依存な振る舞い
 impl BankAccount {
-  pub fn new(id: BankAccountId, user_account_id: UserAccountId, balance: Money) -> Self {
-    Self {
+  /// `balance`が負の場合は`MoneyError::OutOfRange`で口座の生成自体を拒否する。
+  pub fn new(id: BankAccountId, user_account_id: UserAccountId, balance: Money) -> Result<Self, MoneyError> {
+    let balance = balance.constrain::<NonNegative>()?;
+    Ok(Self {
       id,
       user_account_id,
       balance,
-    }
+    })
   }
 
-  pub fn balance(&self) -> &Money {
+  pub fn balance(&self) -> &Money<NonNegative> {
     &self.balance
   }
 
-  pub fn deposit(mut self, amount: Money) -> Result<BankAccount, MoneyError> {
+  pub fn deposit(mut self, amount: Money<NonNegative>) -> Result<BankAccount, MoneyError> {
     self.balance = self.balance.add(amount)?;
     Ok(self)
   }
 
-  pub fn withdraw(mut self, amount: Money) -> Result<BankAccount, MoneyError> {
+  /// 残高を超える引き出しは`balance`が`NonNegative`制約を持つため
+  /// `MoneyError::OutOfRange`で拒否される。
+  pub fn withdraw(mut self, amount: Money<NonNegative>) -> Result<BankAccount, MoneyError> {
     self.balance = self.balance.subtract(amount)?;
     Ok(self)
   }
@@ -44,16 +48,16 @@ impl BankAccount {
 /// 型の定義だけ。いわゆるDCIにおけるメソッドレスロール。
 mod roles {
   use crate::bank_account::BankAccount;
-  use crate::money::{Money, MoneyError};
+  use crate::money::{Money, MoneyError, NonNegative};
 
   pub trait ReceiveRole {
-    fn on_receive(self, money: Money, from: BankAccount) -> Result<Self, MoneyError>
+    fn on_receive(self, money: Money<NonNegative>, from: BankAccount) -> Result<Self, MoneyError>
     where
       Self: Sized;
   }
 
   pub trait SenderRole<T> {
-    fn send(self, money: Money, to: T) -> Result<(Self, T), MoneyError>
+    fn send(self, money: Money<NonNegative>, to: T) -> Result<(Self, T), MoneyError>
     where
       Self: Sized;
   }
@@ -61,11 +65,11 @@ mod roles {
 
 mod role_impl {
   use crate::bank_account::roles::{ReceiveRole, SenderRole};
-  use crate::{BankAccount, Money, MoneyError};
+  use crate::{BankAccount, Money, MoneyError, NonNegative};
 
   /// 送金先のロールの実装。メソッドフルロール。
   impl ReceiveRole for BankAccount {
-    fn on_receive(self, money: Money, _from: BankAccount) -> Result<Self, MoneyError>
+    fn on_receive(self, money: Money<NonNegative>, _from: BankAccount) -> Result<Self, MoneyError>
     where
       Self: Sized,
     {
@@ -76,7 +80,7 @@ mod role_impl {
 
   /// 送金元のロールの実装。メソッドフルロール。
   impl<T: ReceiveRole> SenderRole<T> for BankAccount {
-    fn send(self, money: Money, to: T) -> Result<(Self, T), MoneyError>
+    fn send(self, money: Money<NonNegative>, to: T) -> Result<(Self, T), MoneyError>
     where
       Self: Sized,
     {
@@ -90,7 +94,7 @@ mod role_impl {
 /// 送金コンテキスト
 /// BankAccountには非依存。送金できるT型として定義する。
 mod context {
-  use crate::{Money, MoneyError};
+  use crate::{BankAccount, ExchangeRate, Money, MoneyError, NonNegative};
   use crate::bank_account::roles::{ReceiveRole, SenderRole};
 
   pub struct TransferContext<T: ReceiveRole, F: SenderRole<T>> {
@@ -102,10 +106,36 @@ mod context {
     pub fn new(from: F, to: T) -> Self {
       Self { from, to }
     }
-    pub fn transfer(self, money: Money) -> Result<(F, T), MoneyError> {
+    pub fn transfer(self, money: Money<NonNegative>) -> Result<(F, T), MoneyError> {
       self.from.send(money, self.to)
     }
   }
+
+  /// 異なる通貨間で送金するコンテキスト。送金元から引き落とした金額を為替レートで変換してから
+  /// 受取先の`on_receive`を呼び出す。
+  /// `SenderRole`を介さず`withdraw`を直接呼ぶため、送金元は`BankAccount`に固定される。
+  pub struct CrossCurrencyTransferContext<T: ReceiveRole> {
+    from: BankAccount,
+    to: T,
+    exchange_rate: ExchangeRate,
+  }
+
+  impl<T: ReceiveRole> CrossCurrencyTransferContext<T> {
+    pub fn new(from: BankAccount, to: T, exchange_rate: ExchangeRate) -> Self {
+      Self {
+        from,
+        to,
+        exchange_rate,
+      }
+    }
+
+    pub fn transfer(self, money: Money<NonNegative>) -> Result<(BankAccount, T), MoneyError> {
+      let converted = self.exchange_rate.convert(&money)?;
+      let new_from = self.from.withdraw(money)?;
+      let new_to = self.to.on_receive(converted, new_from.clone())?;
+      Ok((new_from, new_to))
+    }
+  }
 }
 
 #[cfg(test)]
@@ -113,6 +143,12 @@ mod tests {
   use iso_4217::CurrencyCode;
   use rust_decimal::Decimal;
   use crate::{BankAccount, BankAccountId, UserAccountId, Money};
+  use crate::money::NonNegative;
+
+  /// テストで使う金額は常に非負なので、その前提のもとで`Money<NonNegative>`に変換する。
+  fn nn(money: Money) -> Money<NonNegative> {
+    money.constrain().expect("test amounts are always non-negative")
+  }
 
   #[test]
   fn test_dci() {
@@ -120,17 +156,68 @@ mod tests {
       BankAccountId(1),
       UserAccountId(1),
       Money::zero(CurrencyCode::JPY),
-    );
-    let new_ba1 = ba1.deposit(Money::yens_i32(1000)).unwrap();
+    )
+    .unwrap();
+    let new_ba1 = ba1.deposit(nn(Money::yens_i32(1000))).unwrap();
     let ba2 = BankAccount::new(
       BankAccountId(2),
       UserAccountId(1),
       Money::zero(CurrencyCode::JPY),
-    );
+    )
+    .unwrap();
 
     use crate::bank_account::context::TransferContext;
     let context: TransferContext<BankAccount, BankAccount> = TransferContext::new(new_ba1, ba2);
-    let (from, to) = context.transfer(Money::yens_i32(10)).unwrap();
+    let (from, to) = context.transfer(nn(Money::yens_i32(10))).unwrap();
     println!("from = {:?}, to = {:?}", from, to);
   }
+
+  #[test]
+  fn test_cross_currency_transfer() {
+    use std::str::FromStr;
+    use crate::ExchangeRate;
+    use crate::bank_account::context::CrossCurrencyTransferContext;
+
+    let ba1 = BankAccount::new(
+      BankAccountId(1),
+      UserAccountId(1),
+      Money::zero(CurrencyCode::JPY),
+    )
+    .unwrap();
+    let new_ba1 = ba1.deposit(nn(Money::yens_i32(1000))).unwrap();
+    let ba2 = BankAccount::new(
+      BankAccountId(2),
+      UserAccountId(1),
+      Money::zero(CurrencyCode::USD),
+    )
+    .unwrap();
+
+    let exchange_rate = ExchangeRate::new(
+      CurrencyCode::JPY,
+      CurrencyCode::USD,
+      Decimal::from_str("0.0067").unwrap(),
+    );
+    let context = CrossCurrencyTransferContext::new(new_ba1, ba2, exchange_rate);
+    let (from, to) = context.transfer(nn(Money::yens_i32(1000))).unwrap();
+
+    assert_eq!(*from.balance(), nn(Money::zero(CurrencyCode::JPY)));
+    assert_eq!(*to.balance(), nn(Money::dollars(Decimal::from_str("6.70").unwrap())));
+  }
+
+  #[test]
+  fn test_withdraw_overdraw_is_rejected() {
+    use crate::MoneyError;
+
+    let ba = BankAccount::new(
+      BankAccountId(1),
+      UserAccountId(1),
+      Money::zero(CurrencyCode::JPY),
+    )
+    .unwrap();
+    let ba = ba.deposit(nn(Money::yens_i32(1000))).unwrap();
+
+    let err = ba.withdraw(nn(Money::yens_i32(2000))).unwrap_err();
+
+    assert_eq!(err, MoneyError::OutOfRange);
+  }
 }
@@ -0,0 +1,368 @@
+use std::collections::HashMap;
+
+use iso_4217::CurrencyCode;
+
+use crate::money::{Money, MoneyError, NonNegative};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ClientId(pub u16);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TransactionId(pub u32);
+
+/// 台帳が処理する5種類の操作。
+#[derive(Debug, Clone, PartialEq)]
+pub enum Transaction {
+  Deposit {
+    client_id: ClientId,
+    tx_id: TransactionId,
+    amount: Money<NonNegative>,
+  },
+  Withdrawal {
+    client_id: ClientId,
+    tx_id: TransactionId,
+    amount: Money<NonNegative>,
+  },
+  Dispute {
+    client_id: ClientId,
+    tx_id: TransactionId,
+  },
+  Resolve {
+    client_id: ClientId,
+    tx_id: TransactionId,
+  },
+  Chargeback {
+    client_id: ClientId,
+    tx_id: TransactionId,
+  },
+}
+
+impl Transaction {
+  fn client_id(&self) -> ClientId {
+    match self {
+      Transaction::Deposit { client_id, .. }
+      | Transaction::Withdrawal { client_id, .. }
+      | Transaction::Dispute { client_id, .. }
+      | Transaction::Resolve { client_id, .. }
+      | Transaction::Chargeback { client_id, .. } => *client_id,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DepositStatus {
+  Normal,
+  Disputed,
+  ChargedBack,
+}
+
+#[derive(Debug, Clone)]
+struct DepositRecord {
+  client_id: ClientId,
+  amount: Money<NonNegative>,
+  status: DepositStatus,
+}
+
+#[derive(Debug, Clone)]
+struct Account {
+  available: Money<NonNegative>,
+  held: Money<NonNegative>,
+  locked: bool,
+}
+
+impl Account {
+  fn new(currency: CurrencyCode) -> Self {
+    let zero = || {
+      Money::zero(currency)
+        .constrain::<NonNegative>()
+        .expect("zero is always within NonNegative's range")
+    };
+    Self {
+      available: zero(),
+      held: zero(),
+      locked: false,
+    }
+  }
+}
+
+/// クライアントごとの最終残高のスナップショット。
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountSnapshot {
+  pub client_id: ClientId,
+  pub available: Money<NonNegative>,
+  pub held: Money<NonNegative>,
+  pub total: Money<NonNegative>,
+  pub locked: bool,
+}
+
+/// 入金/出金/異議申立/解決/チャージバックのストリームを再生する台帳エンジン。
+/// `TransferContext`が単発の送金しか扱わないのに対し、こちらは取引履歴を保持し
+/// 異議申立が過去の入金を参照できるようにする。
+pub struct Ledger {
+  currency: CurrencyCode,
+  accounts: HashMap<ClientId, Account>,
+  deposits: HashMap<TransactionId, DepositRecord>,
+}
+
+impl Ledger {
+  pub fn new(currency: CurrencyCode) -> Self {
+    Self {
+      currency,
+      accounts: HashMap::new(),
+      deposits: HashMap::new(),
+    }
+  }
+
+  /// 取引を1件処理する。ロック済み口座宛の取引や、不明・状態の合わない参照を持つ
+  /// 異議申立/解決/チャージバックは無視する。残高不足の出金も無視する。
+  pub fn process(&mut self, tx: Transaction) -> Result<(), MoneyError> {
+    let client_id = tx.client_id();
+    if self.accounts.get(&client_id).map_or(false, |a| a.locked) {
+      return Ok(());
+    }
+
+    match tx {
+      Transaction::Deposit {
+        client_id,
+        tx_id,
+        amount,
+      } => self.deposit(client_id, tx_id, amount),
+      Transaction::Withdrawal {
+        client_id, amount, ..
+      } => self.withdraw(client_id, amount),
+      Transaction::Dispute { client_id, tx_id } => self.dispute(client_id, tx_id),
+      Transaction::Resolve { client_id, tx_id } => self.resolve(client_id, tx_id),
+      Transaction::Chargeback { client_id, tx_id } => self.chargeback(client_id, tx_id),
+    }
+  }
+
+  /// クライアントごとの最終状態を返す。
+  pub fn snapshot(&self) -> Vec<AccountSnapshot> {
+    self
+      .accounts
+      .iter()
+      .map(|(client_id, account)| AccountSnapshot {
+        client_id: *client_id,
+        available: account.available.clone(),
+        held: account.held.clone(),
+        total: account
+          .available
+          .clone()
+          .add(account.held.clone())
+          .expect("available + held must stay within Money<NonNegative>'s range"),
+        locked: account.locked,
+      })
+      .collect()
+  }
+
+  fn account_mut(&mut self, client_id: ClientId) -> &mut Account {
+    let currency = self.currency;
+    self
+      .accounts
+      .entry(client_id)
+      .or_insert_with(|| Account::new(currency))
+  }
+
+  fn deposit(
+    &mut self,
+    client_id: ClientId,
+    tx_id: TransactionId,
+    amount: Money<NonNegative>,
+  ) -> Result<(), MoneyError> {
+    let account = self.account_mut(client_id);
+    account.available = account.available.clone().add(amount.clone())?;
+    self.deposits.insert(
+      tx_id,
+      DepositRecord {
+        client_id,
+        amount,
+        status: DepositStatus::Normal,
+      },
+    );
+    Ok(())
+  }
+
+  fn withdraw(&mut self, client_id: ClientId, amount: Money<NonNegative>) -> Result<(), MoneyError> {
+    let account = self.account_mut(client_id);
+    if let Ok(new_available) = account.available.clone().subtract(amount) {
+      account.available = new_available;
+    }
+    Ok(())
+  }
+
+  fn dispute(&mut self, client_id: ClientId, tx_id: TransactionId) -> Result<(), MoneyError> {
+    let amount = match self.deposits.get(&tx_id) {
+      Some(record) if record.client_id == client_id && record.status == DepositStatus::Normal => {
+        record.amount.clone()
+      }
+      _ => return Ok(()),
+    };
+
+    let account = self.account_mut(client_id);
+    account.available = account.available.clone().subtract(amount.clone())?;
+    account.held = account.held.clone().add(amount)?;
+    self.deposits.get_mut(&tx_id).unwrap().status = DepositStatus::Disputed;
+    Ok(())
+  }
+
+  fn resolve(&mut self, client_id: ClientId, tx_id: TransactionId) -> Result<(), MoneyError> {
+    let amount = match self.deposits.get(&tx_id) {
+      Some(record) if record.client_id == client_id && record.status == DepositStatus::Disputed => {
+        record.amount.clone()
+      }
+      _ => return Ok(()),
+    };
+
+    let account = self.account_mut(client_id);
+    account.held = account.held.clone().subtract(amount.clone())?;
+    account.available = account.available.clone().add(amount)?;
+    self.deposits.get_mut(&tx_id).unwrap().status = DepositStatus::Normal;
+    Ok(())
+  }
+
+  fn chargeback(&mut self, client_id: ClientId, tx_id: TransactionId) -> Result<(), MoneyError> {
+    let amount = match self.deposits.get(&tx_id) {
+      Some(record) if record.client_id == client_id && record.status == DepositStatus::Disputed => {
+        record.amount.clone()
+      }
+      _ => return Ok(()),
+    };
+
+    let account = self.account_mut(client_id);
+    account.held = account.held.clone().subtract(amount)?;
+    account.locked = true;
+    self.deposits.get_mut(&tx_id).unwrap().status = DepositStatus::ChargedBack;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use iso_4217::CurrencyCode;
+
+  use super::*;
+  use crate::money::Money;
+
+  /// テストの金額は常に非負なので、その前提のもとで`Money<NonNegative>`に変換する。
+  fn nn(amount: i32) -> Money<NonNegative> {
+    Money::dollars_i32(amount)
+      .constrain()
+      .expect("test amounts are always non-negative")
+  }
+
+  fn deposit(client_id: u16, tx_id: u32, amount: i32) -> Transaction {
+    Transaction::Deposit {
+      client_id: ClientId(client_id),
+      tx_id: TransactionId(tx_id),
+      amount: nn(amount),
+    }
+  }
+
+  fn withdrawal(client_id: u16, tx_id: u32, amount: i32) -> Transaction {
+    Transaction::Withdrawal {
+      client_id: ClientId(client_id),
+      tx_id: TransactionId(tx_id),
+      amount: nn(amount),
+    }
+  }
+
+  fn snapshot_for(ledger: &Ledger, client_id: u16) -> AccountSnapshot {
+    ledger
+      .snapshot()
+      .into_iter()
+      .find(|s| s.client_id == ClientId(client_id))
+      .unwrap()
+  }
+
+  #[test]
+  fn test_deposit_and_withdrawal() {
+    let mut ledger = Ledger::new(CurrencyCode::USD);
+    ledger.process(deposit(1, 1, 100)).unwrap();
+    ledger.process(withdrawal(1, 2, 40)).unwrap();
+
+    let snapshot = snapshot_for(&ledger, 1);
+    assert_eq!(snapshot.available, nn(60));
+    assert_eq!(snapshot.held, nn(0));
+    assert!(!snapshot.locked);
+  }
+
+  #[test]
+  fn test_withdrawal_ignored_when_insufficient() {
+    let mut ledger = Ledger::new(CurrencyCode::USD);
+    ledger.process(deposit(1, 1, 10)).unwrap();
+    ledger.process(withdrawal(1, 2, 100)).unwrap();
+
+    let snapshot = snapshot_for(&ledger, 1);
+    assert_eq!(snapshot.available, nn(10));
+  }
+
+  #[test]
+  fn test_dispute_then_chargeback_locks_account() {
+    let mut ledger = Ledger::new(CurrencyCode::USD);
+    ledger.process(deposit(1, 1, 100)).unwrap();
+    ledger
+      .process(Transaction::Dispute {
+        client_id: ClientId(1),
+        tx_id: TransactionId(1),
+      })
+      .unwrap();
+
+    let disputed = snapshot_for(&ledger, 1);
+    assert_eq!(disputed.available, nn(0));
+    assert_eq!(disputed.held, nn(100));
+
+    ledger
+      .process(Transaction::Chargeback {
+        client_id: ClientId(1),
+        tx_id: TransactionId(1),
+      })
+      .unwrap();
+
+    let charged_back = snapshot_for(&ledger, 1);
+    assert_eq!(charged_back.held, nn(0));
+    assert!(charged_back.locked);
+
+    ledger.process(deposit(1, 2, 50)).unwrap();
+    let after_lock = snapshot_for(&ledger, 1);
+    assert_eq!(after_lock.available, nn(0));
+  }
+
+  #[test]
+  fn test_dispute_then_resolve() {
+    let mut ledger = Ledger::new(CurrencyCode::USD);
+    ledger.process(deposit(1, 1, 100)).unwrap();
+    ledger
+      .process(Transaction::Dispute {
+        client_id: ClientId(1),
+        tx_id: TransactionId(1),
+      })
+      .unwrap();
+    ledger
+      .process(Transaction::Resolve {
+        client_id: ClientId(1),
+        tx_id: TransactionId(1),
+      })
+      .unwrap();
+
+    let snapshot = snapshot_for(&ledger, 1);
+    assert_eq!(snapshot.available, nn(100));
+    assert_eq!(snapshot.held, nn(0));
+    assert!(!snapshot.locked);
+  }
+
+  #[test]
+  fn test_dispute_unknown_transaction_is_ignored() {
+    let mut ledger = Ledger::new(CurrencyCode::USD);
+    ledger.process(deposit(1, 1, 100)).unwrap();
+    ledger
+      .process(Transaction::Dispute {
+        client_id: ClientId(1),
+        tx_id: TransactionId(999),
+      })
+      .unwrap();
+
+    let snapshot = snapshot_for(&ledger, 1);
+    assert_eq!(snapshot.available, nn(100));
+    assert_eq!(snapshot.held, nn(0));
+  }
+}
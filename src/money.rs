@@ -1,26 +1,84 @@
 use std::cmp::Ordering;
+use std::fmt;
 use std::hash::{Hash, Hasher};
-use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::marker::PhantomData;
+use std::ops::{Add, Div, Mul, Neg, RangeInclusive, Sub};
 use std::str::FromStr;
 
 use iso_4217::CurrencyCode;
-use rust_decimal::Decimal;
+use rust_decimal::{Decimal, RoundingStrategy};
 use rust_decimal::prelude::{FromPrimitive, Zero};
 
+/// `Money`が取りうる金額の範囲を表す制約。型パラメータとして使う。
+pub trait Constraint {
+  fn range() -> RangeInclusive<Decimal>;
+}
+
+/// 符号を問わない、制約のないデフォルトの制約。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignedAllowed;
+
+impl Constraint for SignedAllowed {
+  fn range() -> RangeInclusive<Decimal> {
+    Decimal::MIN..=Decimal::MAX
+  }
+}
+
+/// 0以上の金額のみを許す制約。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NonNegative;
+
+impl Constraint for NonNegative {
+  fn range() -> RangeInclusive<Decimal> {
+    Decimal::ZERO..=Decimal::MAX
+  }
+}
+
 #[derive(Debug, Clone, PartialEq)]
-pub struct Money {
-  pub amount: Decimal,
-  pub currency: CurrencyCode,
+pub struct Money<C: Constraint = SignedAllowed> {
+  amount: Decimal,
+  currency: CurrencyCode,
+  _constraint: PhantomData<C>,
 }
 
 #[derive(Debug, PartialEq)]
 pub enum MoneyError {
   NotSameCurrencyError,
+  InvalidRatios,
+  DivideByZero,
+  OutOfRange,
 }
 
-impl Eq for Money {}
+/// `Money::divided_by_with`/`Money::times_with`で使う端数処理の方式。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RoundStrategy {
+  /// 四捨五入(0から遠い方へ丸める)
+  HalfUp,
+  /// 銀行丸め(最近接偶数への丸め)
+  HalfEven,
+  /// 切り上げ
+  Ceiling,
+  /// 切り捨て
+  Floor,
+  /// 0への切り捨て
+  Truncate,
+}
 
-impl Hash for Money {
+impl RoundStrategy {
+  fn to_rounding_strategy(self) -> RoundingStrategy {
+    match self {
+      RoundStrategy::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+      RoundStrategy::HalfEven => RoundingStrategy::MidpointNearestEven,
+      RoundStrategy::Ceiling => RoundingStrategy::ToPositiveInfinity,
+      RoundStrategy::Floor => RoundingStrategy::ToNegativeInfinity,
+      RoundStrategy::Truncate => RoundingStrategy::ToZero,
+    }
+  }
+}
+
+impl<C: Constraint + PartialEq> Eq for Money<C> {}
+
+impl<C: Constraint> Hash for Money<C> {
   fn hash<H>(&self, state: &mut H)
   where
     H: Hasher,
@@ -30,7 +88,7 @@ impl Hash for Money {
   }
 }
 
-impl PartialOrd for Money {
+impl<C: Constraint + PartialEq> PartialOrd for Money<C> {
   fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
     if self.currency != other.currency {
       None
@@ -44,53 +102,53 @@ impl PartialOrd for Money {
   }
 }
 
-impl Add for Money {
-  type Output = Money;
+impl<C: Constraint> Add for Money<C> {
+  type Output = Money<C>;
 
   fn add(self, rhs: Self) -> Self::Output {
     Money::add(self, rhs).unwrap_or_else(|err| panic!(format!("{:?}", err)))
   }
 }
 
-impl Sub for Money {
-  type Output = Money;
+impl<C: Constraint> Sub for Money<C> {
+  type Output = Money<C>;
 
   fn sub(self, rhs: Self) -> Self::Output {
     Money::subtract(self, rhs).unwrap_or_else(|err| panic!(format!("{:?}", err)))
   }
 }
 
-impl Mul<Decimal> for Money {
-  type Output = Money;
+impl Mul<Decimal> for Money<SignedAllowed> {
+  type Output = Money<SignedAllowed>;
 
   fn mul(self, rhs: Decimal) -> Self::Output {
     Money::times(self, rhs)
   }
 }
 
-impl Div<Decimal> for Money {
-  type Output = Money;
+impl Div<Decimal> for Money<SignedAllowed> {
+  type Output = Money<SignedAllowed>;
 
   fn div(self, rhs: Decimal) -> Self::Output {
     Money::divided_by(self, rhs)
   }
 }
 
-impl Neg for Money {
-  type Output = Money;
+impl<C: Constraint> Neg for Money<C> {
+  type Output = Money<C>;
 
   fn neg(self) -> Self::Output {
-    Money::negated(self)
+    Money::negated(self).unwrap_or_else(|err| panic!(format!("{:?}", err)))
   }
 }
 
-impl From<(Decimal, CurrencyCode)> for Money {
+impl From<(Decimal, CurrencyCode)> for Money<SignedAllowed> {
   fn from((amount, currency): (Decimal, CurrencyCode)) -> Self {
     Money::new(amount, currency)
   }
 }
 
-impl From<(&str, CurrencyCode)> for Money {
+impl From<(&str, CurrencyCode)> for Money<SignedAllowed> {
   fn from((amount, currency): (&str, CurrencyCode)) -> Self {
     let a = Decimal::from_str(amount).unwrap();
     Money::new(a, currency)
@@ -99,7 +157,7 @@ impl From<(&str, CurrencyCode)> for Money {
 
 macro_rules! from_numeric_impl {
   ($($t:ty)*) => ($(
-    impl From<($t, CurrencyCode)> for Money {
+    impl From<($t, CurrencyCode)> for Money<SignedAllowed> {
       fn from((amount, currency): ($t, CurrencyCode)) -> Self {
         let mut a = Decimal::from(amount);
         a.rescale(currency.digit().unwrap() as u32);
@@ -111,15 +169,148 @@ macro_rules! from_numeric_impl {
 
 from_numeric_impl! {i8 i16 i32 i64 u8 u16 u32 u64}
 
-impl Money {
-  pub fn new(amount: Decimal, currency: CurrencyCode) -> Self {
+impl<C: Constraint> Money<C> {
+  /// 制約`C`を検査せずに金額を通貨の最小単位へ丸めて組み立てる。呼び出し側が
+  /// 結果の妥当性(符号や範囲)を別途保証できる場合にのみ使う内部ヘルパー。
+  fn raw(amount: Decimal, currency: CurrencyCode) -> Self {
     let mut a = amount;
-
     a.rescale(currency.digit().unwrap() as u32);
     Self {
       amount: a,
       currency,
+      _constraint: PhantomData,
+    }
+  }
+
+  /// 制約`C2`を満たすかどうかを実行時に検査し、満たす場合のみ別の制約に変換する。
+  pub fn constrain<C2: Constraint>(self) -> Result<Money<C2>, MoneyError> {
+    if !C2::range().contains(&self.amount) {
+      return Err(MoneyError::OutOfRange);
+    }
+    Ok(Money {
+      amount: self.amount,
+      currency: self.currency,
+      _constraint: PhantomData,
+    })
+  }
+
+  pub fn abs(&self) -> Self {
+    Self {
+      amount: self.amount.abs(),
+      currency: self.currency,
+      _constraint: PhantomData,
+    }
+  }
+
+  pub fn is_positive(&self) -> bool {
+    self.amount > Decimal::zero()
+  }
+
+  pub fn is_negative(&self) -> bool {
+    self.amount < Decimal::zero()
+  }
+
+  pub fn is_zero(&self) -> bool {
+    self.amount.is_zero()
+  }
+
+  /// 符号を反転する。結果が制約`C`の範囲外になる場合は`MoneyError::OutOfRange`を返す。
+  pub fn negated(self) -> Result<Self, MoneyError> {
+    let amount = -self.amount;
+    if !C::range().contains(&amount) {
+      return Err(MoneyError::OutOfRange);
+    }
+    Ok(Self {
+      amount,
+      currency: self.currency,
+      _constraint: PhantomData,
+    })
+  }
+
+  /// 結果が制約`C`の範囲外になる場合は`MoneyError::OutOfRange`を返す。
+  //noinspection RsExternalLinter
+  pub fn add(self, other: Self) -> Result<Self, MoneyError> {
+    if self.currency != other.currency {
+      return Err(MoneyError::NotSameCurrencyError);
     }
+    let amount = self.amount + other.amount;
+    if !C::range().contains(&amount) {
+      return Err(MoneyError::OutOfRange);
+    }
+    Ok(Self {
+      amount,
+      currency: self.currency,
+      _constraint: PhantomData,
+    })
+  }
+
+  /// 結果が制約`C`の範囲外になる場合は`MoneyError::OutOfRange`を返す。
+  pub fn subtract(self, other: Self) -> Result<Self, MoneyError> {
+    if self.currency != other.currency {
+      return Err(MoneyError::NotSameCurrencyError);
+    }
+    let amount = self.amount - other.amount;
+    if !C::range().contains(&amount) {
+      return Err(MoneyError::OutOfRange);
+    }
+    Ok(Self {
+      amount,
+      currency: self.currency,
+      _constraint: PhantomData,
+    })
+  }
+
+  /// `ratios`の比率に応じて金額を分配する。最小通貨単位の端数は最大剰余法で配分するため、
+  /// 返される各`Money`の合計は必ず元の金額と一致する。
+  pub fn allocate(&self, ratios: &[u64]) -> Result<Vec<Self>, MoneyError> {
+    let total_ratio: u128 = ratios.iter().map(|r| *r as u128).sum();
+    if ratios.is_empty() || total_ratio == 0 {
+      return Err(MoneyError::InvalidRatios);
+    }
+
+    let digit = self.currency.digit().unwrap() as u32;
+    let mut amount = self.amount;
+    amount.rescale(digit);
+    let total_minor = amount.mantissa();
+    let total_ratio = total_ratio as i128;
+
+    let mut shares = Vec::with_capacity(ratios.len());
+    let mut remainders = Vec::with_capacity(ratios.len());
+    let mut allocated: i128 = 0;
+    for (i, &ratio) in ratios.iter().enumerate() {
+      let numerator = total_minor * ratio as i128;
+      let share = numerator.div_euclid(total_ratio);
+      let remainder = numerator.rem_euclid(total_ratio);
+      shares.push(share);
+      remainders.push((i, remainder));
+      allocated += share;
+    }
+
+    let leftover = (total_minor - allocated) as usize;
+    remainders.sort_by(|(ia, ra), (ib, rb)| rb.cmp(ra).then(ia.cmp(ib)));
+    for &(i, _) in remainders.iter().take(leftover) {
+      shares[i] += 1;
+    }
+
+    Ok(
+      shares
+        .into_iter()
+        .map(|share| Self::raw(Decimal::new(share as i64, digit), self.currency))
+        .collect(),
+    )
+  }
+
+  /// 金額を`n`等分する。端数の配分規則は[`Money::allocate`]と同じ。
+  pub fn split(&self, n: usize) -> Result<Vec<Self>, MoneyError> {
+    self.allocate(&vec![1u64; n])
+  }
+}
+
+/// 符号を問わない基本的な構築方法。これらは制約の範囲を検査しないため`C`を`SignedAllowed`に固定している。
+/// 制約付きの`Money<C>`が必要な場合はここで作った値を[`Money::constrain`]に通す。
+impl Money<SignedAllowed> {
+  pub fn new(amount: Decimal, currency: CurrencyCode) -> Self {
+    Self::raw(amount, currency)
   }
 
   pub fn dollars(amount: Decimal) -> Self {
@@ -154,68 +345,171 @@ impl Money {
     Self::new(Decimal::zero(), currency)
   }
 
-  pub fn abs(&self) -> Self {
+  pub fn times(self, factor: Decimal) -> Self {
     Self {
-      amount: self.amount.abs(),
+      amount: self.amount * factor,
       currency: self.currency,
+      _constraint: PhantomData,
     }
   }
 
-  pub fn is_positive(&self) -> bool {
-    self.amount > Decimal::zero()
+  pub fn divided_by(self, divisor: Decimal) -> Self {
+    Self {
+      amount: self.amount / divisor,
+      currency: self.currency,
+      _constraint: PhantomData,
+    }
   }
 
-  pub fn is_negative(&self) -> bool {
-    self.amount < Decimal::zero()
+  /// `strategy`に従って通貨の最小単位(`currency.digit()`)に丸めながら乗算する。
+  pub fn times_with(self, factor: Decimal, strategy: RoundStrategy) -> Self {
+    let digit = self.currency.digit().unwrap() as u32;
+    let amount = (self.amount * factor).round_dp_with_strategy(digit, strategy.to_rounding_strategy());
+    Self {
+      amount,
+      currency: self.currency,
+      _constraint: PhantomData,
+    }
   }
 
-  pub fn is_zero(&self) -> bool {
-    self.amount.is_zero()
+  /// `strategy`に従って通貨の最小単位(`currency.digit()`)に丸めながら除算する。
+  /// `divisor`が0の場合は`MoneyError::DivideByZero`を返す。
+  pub fn divided_by_with(self, divisor: Decimal, strategy: RoundStrategy) -> Result<Self, MoneyError> {
+    if divisor.is_zero() {
+      return Err(MoneyError::DivideByZero);
+    }
+    let digit = self.currency.digit().unwrap() as u32;
+    let amount = (self.amount / divisor).round_dp_with_strategy(digit, strategy.to_rounding_strategy());
+    Ok(Self {
+      amount,
+      currency: self.currency,
+      _constraint: PhantomData,
+    })
   }
+}
 
-  pub fn negated(self) -> Self {
+/// 通貨記号を表示するか、通貨コードを表示するか。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolStyle {
+  Symbol,
+  Code,
+}
+
+/// 通貨記号(またはコード)を金額の前に置くか、後に置くか。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolPosition {
+  Prefix,
+  Suffix,
+}
+
+/// [`Money::format_with`]の表示設定。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FormatParams {
+  pub group_separator: char,
+  pub decimal_separator: char,
+  pub symbol_style: SymbolStyle,
+  pub symbol_position: SymbolPosition,
+}
+
+impl Default for FormatParams {
+  fn default() -> Self {
     Self {
-      amount: -self.amount,
-      currency: self.currency,
+      group_separator: ',',
+      decimal_separator: '.',
+      symbol_style: SymbolStyle::Symbol,
+      symbol_position: SymbolPosition::Prefix,
     }
   }
+}
 
-  //noinspection RsExternalLinter
-  pub fn add(self, other: Self) -> Result<Self, MoneyError> {
-    if self.currency != other.currency {
-      Err(MoneyError::NotSameCurrencyError)
-    } else {
-      Ok(Self {
-        amount: self.amount + other.amount,
-        currency: self.currency,
-      })
+/// 主要通貨の記号。それ以外の通貨はISOコード("CHF"等)にフォールバックする。
+fn currency_symbol(currency: CurrencyCode) -> String {
+  match currency {
+    CurrencyCode::USD => "$".to_string(),
+    CurrencyCode::JPY => "¥".to_string(),
+    CurrencyCode::EUR => "€".to_string(),
+    CurrencyCode::GBP => "£".to_string(),
+    other => format!("{:?}", other),
+  }
+}
+
+/// 整数部の文字列を右から3桁ごとに`separator`で区切る。
+fn group_digits(digits: &str, separator: char) -> String {
+  let chars: Vec<char> = digits.chars().collect();
+  let mut groups = Vec::new();
+  let mut end = chars.len();
+  while end > 3 {
+    groups.push(chars[end - 3..end].iter().collect::<String>());
+    end -= 3;
+  }
+  groups.push(chars[..end].iter().collect::<String>());
+  groups.reverse();
+  groups.join(&separator.to_string())
+}
+
+impl<C: Constraint> Money<C> {
+  /// `params`に従って金額を整形する。桁区切り・小数点の文字、記号かコードか、
+  /// 記号の前置/後置をそれぞれ指定できる。
+  pub fn format_with(&self, params: FormatParams) -> String {
+    let sign = if self.amount.is_sign_negative() { "-" } else { "" };
+    let formatted = self.amount.abs().to_string();
+    let (integer_part, fractional_part) = match formatted.split_once('.') {
+      Some((i, f)) => (i, Some(f)),
+      None => (formatted.as_str(), None),
+    };
+
+    let mut number = group_digits(integer_part, params.group_separator);
+    if let Some(fractional) = fractional_part {
+      number.push(params.decimal_separator);
+      number.push_str(fractional);
+    }
+
+    let symbol = match params.symbol_style {
+      SymbolStyle::Symbol => currency_symbol(self.currency),
+      SymbolStyle::Code => format!("{:?}", self.currency),
+    };
+
+    match params.symbol_position {
+      SymbolPosition::Prefix => format!("{}{}{}", sign, symbol, number),
+      SymbolPosition::Suffix => format!("{}{}{}", sign, number, symbol),
     }
   }
+}
 
-  pub fn subtract(self, other: Self) -> Result<Self, MoneyError> {
-    self.add(other.negated())
+impl<C: Constraint> fmt::Display for Money<C> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.format_with(FormatParams::default()))
   }
+}
 
-  pub fn times(self, factor: Decimal) -> Self {
-    Self {
-      amount: self.amount * factor,
-      currency: self.currency,
-    }
+/// 通貨間の為替レート。`from`通貨の金額を`to`通貨の金額に変換する。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExchangeRate {
+  pub from: CurrencyCode,
+  pub to: CurrencyCode,
+  pub rate: Decimal,
+}
+
+impl ExchangeRate {
+  pub fn new(from: CurrencyCode, to: CurrencyCode, rate: Decimal) -> Self {
+    Self { from, to, rate }
   }
 
-  pub fn divided_by(self, divisor: Decimal) -> Self {
-    Self {
-      amount: self.amount / divisor,
-      currency: self.currency,
+  pub fn convert<C: Constraint>(&self, money: &Money<C>) -> Result<Money<C>, MoneyError> {
+    if money.currency != self.from {
+      return Err(MoneyError::NotSameCurrencyError);
     }
+    Ok(Money::raw(money.amount * self.rate, self.to))
   }
 }
 
 #[cfg(test)]
 mod tests {
+  use std::str::FromStr;
+
   use iso_4217::CurrencyCode;
   use rust_decimal::Decimal;
-  use crate::money::{Money};
+  use crate::money::{ExchangeRate, FormatParams, Money, MoneyError, NonNegative, RoundStrategy, SymbolPosition, SymbolStyle};
   use rust_decimal::prelude::{Zero, FromPrimitive};
 
   #[test]
@@ -265,4 +559,164 @@ mod tests {
       Money::new(Decimal::from_i32(3).unwrap(), CurrencyCode::USD)
     );
   }
+
+  #[test]
+  fn test_allocate() {
+    let m = Money::yens_i32(100);
+    let parts = m.allocate(&[1, 1, 1]).unwrap();
+
+    assert_eq!(parts.len(), 3);
+    assert_eq!(parts[0], Money::yens_i32(34));
+    assert_eq!(parts[1], Money::yens_i32(33));
+    assert_eq!(parts[2], Money::yens_i32(33));
+
+    let total = parts
+      .into_iter()
+      .reduce(|acc, m| acc.add(m).unwrap())
+      .unwrap();
+    assert_eq!(total, m);
+  }
+
+  #[test]
+  fn test_allocate_invalid_ratios() {
+    let m = Money::dollars_i32(10);
+    assert_eq!(m.allocate(&[]).unwrap_err(), MoneyError::InvalidRatios);
+    assert_eq!(
+      m.allocate(&[0, 0]).unwrap_err(),
+      MoneyError::InvalidRatios
+    );
+  }
+
+  #[test]
+  fn test_split() {
+    let m = Money::dollars_i32(10);
+    let parts = m.split(3).unwrap();
+
+    let total = parts
+      .into_iter()
+      .reduce(|acc, m| acc.add(m).unwrap())
+      .unwrap();
+    assert_eq!(total, m);
+  }
+
+  #[test]
+  fn test_exchange_rate_convert() {
+    let rate = ExchangeRate::new(
+      CurrencyCode::JPY,
+      CurrencyCode::USD,
+      Decimal::from_str("0.0067").unwrap(),
+    );
+    let jpy = Money::yens_i32(1000);
+
+    let usd = rate.convert(&jpy).unwrap();
+
+    assert_eq!(usd, Money::dollars(Decimal::from_str("6.70").unwrap()));
+  }
+
+  #[test]
+  fn test_exchange_rate_convert_wrong_currency() {
+    let rate = ExchangeRate::new(
+      CurrencyCode::JPY,
+      CurrencyCode::USD,
+      Decimal::from_str("0.0067").unwrap(),
+    );
+    let usd = Money::dollars_i32(10);
+
+    assert_eq!(
+      rate.convert(&usd).unwrap_err(),
+      MoneyError::NotSameCurrencyError
+    );
+  }
+
+  #[test]
+  fn test_times_with_half_up() {
+    let m = Money::yens_i32(10);
+    let result = m.times_with(Decimal::from_str("0.05").unwrap(), RoundStrategy::HalfUp);
+    assert_eq!(result, Money::yens_i32(1));
+  }
+
+  #[test]
+  fn test_times_with_half_even() {
+    let m = Money::yens_i32(10);
+    let result = m.times_with(Decimal::from_str("0.05").unwrap(), RoundStrategy::HalfEven);
+    assert_eq!(result, Money::yens_i32(0));
+  }
+
+  #[test]
+  fn test_divided_by_with_ceiling_and_floor() {
+    let m = Money::yens_i32(10);
+
+    let ceiling = m
+      .clone()
+      .divided_by_with(Decimal::from_i32(3).unwrap(), RoundStrategy::Ceiling)
+      .unwrap();
+    assert_eq!(ceiling, Money::yens_i32(4));
+
+    let floor = m
+      .divided_by_with(Decimal::from_i32(3).unwrap(), RoundStrategy::Floor)
+      .unwrap();
+    assert_eq!(floor, Money::yens_i32(3));
+  }
+
+  #[test]
+  fn test_divided_by_with_zero() {
+    let m = Money::dollars_i32(10);
+    assert_eq!(
+      m.divided_by_with(Decimal::zero(), RoundStrategy::Truncate)
+        .unwrap_err(),
+      MoneyError::DivideByZero
+    );
+  }
+
+  #[test]
+  fn test_constrain_to_non_negative() {
+    let positive: Money = Money::dollars_i32(10);
+    let non_negative: Money<NonNegative> = positive.constrain().unwrap();
+    assert_eq!(non_negative.amount, Decimal::from_i32(10).unwrap());
+
+    let negative: Money = Money::dollars_i32(-10);
+    assert_eq!(
+      negative.constrain::<NonNegative>().unwrap_err(),
+      MoneyError::OutOfRange
+    );
+  }
+
+  #[test]
+  fn test_non_negative_subtract_out_of_range() {
+    let balance: Money<NonNegative> = Money::dollars_i32(10).constrain().unwrap();
+    let amount: Money<NonNegative> = Money::dollars_i32(20).constrain().unwrap();
+
+    assert_eq!(
+      balance.subtract(amount).unwrap_err(),
+      MoneyError::OutOfRange
+    );
+  }
+
+  #[test]
+  fn test_display_jpy_and_usd() {
+    let jpy = Money::yens_i32(1000);
+    assert_eq!(jpy.to_string(), "¥1,000");
+
+    let usd = Money::dollars_i32(1000);
+    assert_eq!(usd.to_string(), "$1,000.00");
+  }
+
+  #[test]
+  fn test_display_negative_amount() {
+    let m = Money::dollars_i32(-1234);
+    assert_eq!(m.to_string(), "-$1,234.00");
+  }
+
+  #[test]
+  fn test_format_with_code_and_suffix() {
+    let m = Money::dollars_i32(1000);
+    let params = FormatParams {
+      group_separator: '.',
+      decimal_separator: ',',
+      symbol_style: SymbolStyle::Code,
+      symbol_position: SymbolPosition::Suffix,
+    };
+
+    assert_eq!(m.format_with(params), "1.000,00USD");
+  }
 }